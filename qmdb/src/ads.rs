@@ -0,0 +1,472 @@
+//! The authenticated data store: wires together the per-shard storage,
+//! the metadb, and the block-commit pipeline driven by [`AdsWrap`].
+
+use std::sync::Arc;
+
+use parking_lot::RwLock;
+
+use crate::backend::{FileBackend, KvBackend, MemBackend};
+use crate::cache::CacheLoadSummary;
+use crate::config::{Config, MerkelizeFilter};
+use crate::def::{IN_BLOCK_IDX_BITS, OP_DELETE, SHARD_COUNT};
+use crate::metadb::MetaDb;
+use crate::shard::ShardStore;
+use crate::snapshot::{Chunk, ChunkStream, ShardRange, SnapshotError};
+use crate::tasks::{Task, TasksManager};
+use crate::utils::changeset::Operation;
+
+/// Operations collected for the in-flight block, tagged with the height
+/// they'll be committed at once [`AdsWrap::flush`] runs, and whether
+/// [`Config::is_merkelized`] selected this key for proof inclusion.
+struct PendingOp {
+    height: u64,
+    merkelize: bool,
+    op: Operation,
+}
+
+/// One-time setup of the on-disk layout an [`AdsWrap`] expects to find.
+/// A no-op for [`crate::config::Backend::Memory`], which has nothing to
+/// lay out.
+pub struct AdsCore;
+
+impl AdsCore {
+    pub fn init_dir(config: &Config) {
+        if config.backend == crate::config::Backend::File {
+            std::fs::create_dir_all(&config.dir).expect("failed to create ADS directory");
+        }
+    }
+}
+
+fn open_backend(config: &Config) -> Arc<dyn KvBackend> {
+    match config.backend {
+        crate::config::Backend::File => {
+            Arc::new(FileBackend::open(&config.dir).expect("failed to open file backend"))
+        }
+        crate::config::Backend::Memory => Arc::new(MemBackend::new()),
+    }
+}
+
+/// Read/write surface shared across threads. Cloning is cheap: it's an
+/// `Arc` handle into the same shards and metadb as the owning [`AdsWrap`].
+pub struct SharedAds<T: Task> {
+    shards: Arc<Vec<RwLock<ShardStore>>>,
+    metadb: Arc<RwLock<MetaDb>>,
+    pending: Arc<RwLock<Vec<PendingOp>>>,
+    tasks: Arc<RwLock<Option<Arc<TasksManager<T>>>>>,
+    next_height: Arc<RwLock<u64>>,
+    is_merkelized: MerkelizeFilter,
+    ver_window: u64,
+}
+
+impl<T: Task> Clone for SharedAds<T> {
+    fn clone(&self) -> Self {
+        Self {
+            shards: self.shards.clone(),
+            metadb: self.metadb.clone(),
+            pending: self.pending.clone(),
+            tasks: self.tasks.clone(),
+            next_height: self.next_height.clone(),
+            is_merkelized: self.is_merkelized.clone(),
+            ver_window: self.ver_window,
+        }
+    }
+}
+
+/// The read/write operations every shared handle exposes.
+pub trait ADS {
+    /// Reads the latest value for `key_hash` as of `height`, or the
+    /// current head if `height` is [`crate::def::LATEST_HEIGHT`].
+    fn read_entry(&self, height: i64, key_hash: &[u8], key: &[u8], buf: &mut [u8]) -> (usize, bool);
+
+    /// Reads the value `key_hash` held as of block `height`, reconstructed
+    /// from the retained version history. Returns `(0, false)` if the key
+    /// didn't exist yet, or if the version needed has already been pruned
+    /// (see [`Config::ver_window`]).
+    fn read_entry_at_height(&self, height: u64, key_hash: &[u8], key: &[u8], buf: &mut [u8]) -> (usize, bool);
+
+    fn insert_extra_data(&self, height: i64, data: String);
+
+    /// Pumps the task at `task_id` (block height in the high bits, index
+    /// in the low [`IN_BLOCK_IDX_BITS`] bits) into the pipeline.
+    fn add_task(&self, task_id: i64);
+
+    /// Exports the shards in `range` as of `height`, for a peer to stream
+    /// to a node that's catching up. Only the latest committed height is
+    /// currently exportable; `height` must equal the current `curr_height`.
+    fn export_snapshot(&self, height: i64, range: ShardRange) -> ChunkStream;
+}
+
+impl<T: Task> SharedAds<T> {
+    fn shard_for(&self, key_hash: &[u8]) -> usize {
+        crate::utils::byte0_to_shard_id(key_hash[0]) as usize
+    }
+}
+
+impl<T: Task> ADS for SharedAds<T> {
+    fn read_entry(&self, height: i64, key_hash: &[u8], key: &[u8], buf: &mut [u8]) -> (usize, bool) {
+        if height >= 0 {
+            return self.read_entry_at_height(height as u64, key_hash, key, buf);
+        }
+        let shard = &self.shards[self.shard_for(key_hash)];
+        let kh: [u8; 32] = key_hash[..32].try_into().expect("key_hash must be 32 bytes");
+        shard
+            .read()
+            .read_latest(&kh, buf)
+            .expect("entryfile read failed")
+    }
+
+    fn read_entry_at_height(&self, height: u64, key_hash: &[u8], _key: &[u8], buf: &mut [u8]) -> (usize, bool) {
+        let metadb = self.metadb.read();
+        let curr_height = metadb.get_curr_height().max(0) as u64;
+        drop(metadb);
+        let floor = curr_height.saturating_sub(self.ver_window);
+
+        let kh: [u8; 32] = key_hash[..32].try_into().expect("key_hash must be 32 bytes");
+        let shard = &self.shards[self.shard_for(key_hash)];
+        shard
+            .read()
+            .read_at_height(&kh, height, floor, buf)
+            .expect("entryfile read failed")
+    }
+
+    fn insert_extra_data(&self, height: i64, data: String) {
+        self.metadb.write().insert_extra_data(height, data);
+    }
+
+    fn add_task(&self, task_id: i64) {
+        let idx = (task_id & ((1 << IN_BLOCK_IDX_BITS) - 1)) as usize;
+        let height = (task_id >> IN_BLOCK_IDX_BITS) as u64;
+
+        let tasks = self.tasks.read().clone().expect("start_block must be called before add_task");
+        let task = tasks.task_list[idx]
+            .write()
+            .take()
+            .expect("task already pumped");
+
+        let mut pending = self.pending.write();
+        for cset in task.change_sets() {
+            for op in cset.ops() {
+                let merkelize = op.op_type != OP_DELETE && (self.is_merkelized)(&op.key);
+                pending.push(PendingOp {
+                    height,
+                    merkelize,
+                    op: op.clone(),
+                });
+            }
+        }
+        *self.next_height.write() = height;
+    }
+
+    fn export_snapshot(&self, height: i64, range: ShardRange) -> ChunkStream {
+        let curr_height = self.metadb.read().get_curr_height();
+        assert_eq!(
+            height, curr_height,
+            "export_snapshot only supports the current committed height"
+        );
+        ChunkStream::new(self.shards.clone(), range)
+    }
+}
+
+/// Owns the shards and metadb, and drives the block-commit pipeline.
+/// `AdsWrap::new` is the entry point after [`AdsCore::init_dir`] has laid
+/// out the directory.
+pub struct AdsWrap<T: Task> {
+    config: Config,
+    shared: SharedAds<T>,
+    cache_load: CacheLoadSummary,
+}
+
+impl<T: Task> AdsWrap<T> {
+    pub fn new(config: &Config) -> Self {
+        let backend = open_backend(config);
+
+        let mut shards = Vec::with_capacity(SHARD_COUNT);
+        let mut cache_load = CacheLoadSummary::default();
+        for shard_id in 0..SHARD_COUNT {
+            let (store, report) = ShardStore::open(
+                backend.clone(),
+                shard_id as u8,
+                config.load_in_memory,
+                config.cache_size_targets,
+                config.cache_tracker,
+            )
+            .expect("failed to open shard store");
+            cache_load.add(&report);
+            shards.push(RwLock::new(store));
+        }
+
+        let shared = SharedAds {
+            shards: Arc::new(shards),
+            metadb: Arc::new(RwLock::new(MetaDb::open(backend))),
+            pending: Arc::new(RwLock::new(Vec::new())),
+            tasks: Arc::new(RwLock::new(None)),
+            next_height: Arc::new(RwLock::new(0)),
+            is_merkelized: config.is_merkelized.clone(),
+            ver_window: config.ver_window,
+        };
+
+        Self {
+            config: config.clone(),
+            shared,
+            cache_load,
+        }
+    }
+
+    /// Aggregated startup cache-preload metrics, so operators can size
+    /// [`Config::cache_size_targets`] against the working set the store
+    /// actually holds rather than guessing.
+    pub fn cache_load_report(&self) -> CacheLoadSummary {
+        self.cache_load
+    }
+
+    /// Builds a fresh [`AdsWrap`] by replaying a snapshot's chunks instead
+    /// of catching up block-by-block. Each chunk is verified against the
+    /// corresponding entry in `expected_roots` as it's replayed, so a chunk
+    /// tampered with (or sent for the wrong shard) is caught before it's
+    /// trusted; `height` becomes the new instance's `curr_height`.
+    pub fn import_snapshot(
+        config: &Config,
+        height: i64,
+        expected_roots: [[u8; 32]; SHARD_COUNT],
+        chunks: impl Iterator<Item = Chunk>,
+    ) -> Result<Self, SnapshotError> {
+        let backend = open_backend(config);
+
+        let mut shards = Vec::with_capacity(SHARD_COUNT);
+        let mut cache_load = CacheLoadSummary::default();
+        for shard_id in 0..SHARD_COUNT {
+            let (store, report) = ShardStore::open(
+                backend.clone(),
+                shard_id as u8,
+                config.load_in_memory,
+                config.cache_size_targets,
+                config.cache_tracker,
+            )?;
+            cache_load.add(&report);
+            shards.push(RwLock::new(store));
+        }
+
+        for chunk in chunks {
+            let mut store = shards[chunk.shard_id as usize].write();
+            for entry in &chunk.entries {
+                let key_hash = crate::utils::hasher::hash(&entry.key);
+                store.import_entry(
+                    key_hash,
+                    &entry.key,
+                    &entry.value,
+                    entry.height,
+                    entry.merkelize,
+                    entry.leaf_pos,
+                )?;
+            }
+            if store.root() != expected_roots[chunk.shard_id as usize] {
+                return Err(SnapshotError::RootMismatch {
+                    shard_id: chunk.shard_id,
+                });
+            }
+        }
+
+        let mut metadb = MetaDb::open(backend.clone());
+        metadb.set_curr_height(height);
+        for (shard_id, root) in expected_roots.iter().enumerate() {
+            metadb.set_root(shard_id as u8, *root);
+        }
+
+        let shared = SharedAds {
+            shards: Arc::new(shards),
+            metadb: Arc::new(RwLock::new(metadb)),
+            pending: Arc::new(RwLock::new(Vec::new())),
+            tasks: Arc::new(RwLock::new(None)),
+            next_height: Arc::new(RwLock::new(height.max(0) as u64)),
+            is_merkelized: config.is_merkelized.clone(),
+            ver_window: config.ver_window,
+        };
+
+        Ok(Self {
+            config: config.clone(),
+            shared,
+            cache_load,
+        })
+    }
+
+    pub fn get_metadb(&self) -> Arc<RwLock<MetaDb>> {
+        self.shared.metadb.clone()
+    }
+
+    pub fn get_shared(&self) -> SharedAds<T> {
+        self.shared.clone()
+    }
+
+    /// Registers the tasks scheduled for `height`; callers then pump each
+    /// one in through [`ADS::add_task`] before calling [`Self::flush`].
+    pub fn start_block(&mut self, _height: i64, tasks: Arc<TasksManager<T>>) {
+        *self.shared.tasks.write() = Some(tasks);
+        self.shared.pending.write().clear();
+    }
+
+    /// Applies every pending op to its shard, recomputes roots, advances
+    /// `curr_height`, and prunes versions that have fallen outside the
+    /// configured `ver_window`.
+    pub fn flush(&mut self) {
+        let height = *self.shared.next_height.read();
+        let pending = std::mem::take(&mut *self.shared.pending.write());
+
+        for pending_op in pending {
+            let op = pending_op.op;
+            let shard = &self.shared.shards[op.shard_id as usize];
+            let mut shard = shard.write();
+            let value: &[u8] = if op.op_type == OP_DELETE { &[] } else { &op.value };
+            shard
+                .write_entry(op.key_hash, &op.key, value, pending_op.height, pending_op.merkelize)
+                .expect("entryfile write failed");
+        }
+
+        let mut metadb = self.shared.metadb.write();
+        for (shard_id, shard) in self.shared.shards.iter().enumerate() {
+            metadb.set_root(shard_id as u8, shard.read().root());
+        }
+        metadb.set_curr_height(height as i64);
+        drop(metadb);
+
+        if height >= self.config.ver_window {
+            let floor = height - self.config.ver_window;
+            for shard in self.shared.shards.iter() {
+                shard.write().prune_before(floor).expect("entryfile compaction failed");
+            }
+        }
+
+        // The block's writes are all durable now, so entries it touched
+        // no longer need eviction-protection in the read cache.
+        for shard in self.shared.shards.iter() {
+            shard.read().clear_cache_dirty();
+        }
+
+        *self.shared.tasks.write() = None;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::def::OP_CREATE;
+    use crate::entryfile::EntryBz;
+    use crate::snapshot::ShardRange;
+    use crate::test_helper::SimpleTask;
+    use crate::utils::byte0_to_shard_id;
+    use crate::utils::changeset::ChangeSet;
+    use crate::utils::hasher;
+
+    #[test]
+    fn cache_load_report_is_queryable_after_open() {
+        // Regression test: the per-shard preload metrics used to only be
+        // `println!`'d once at startup and discarded, with no way for a
+        // caller to retrieve them afterward.
+        let config = Config::in_memory().with_in_memory_cache(crate::cache::SizeTargets::bytes(1 << 20, 1 << 20));
+        let ads = AdsWrap::<SimpleTask>::new(&config);
+
+        // The backend is empty at open time, so nothing was preloaded;
+        // what matters is that the aggregated report is programmatically
+        // reachable at all rather than only printed.
+        let report = ads.cache_load_report();
+        assert_eq!(report.total_resident_bytes, 0);
+    }
+
+    fn write_one_block(ads: &mut AdsWrap<SimpleTask>, height: i64, key: &[u8], value: &[u8]) {
+        let kh = hasher::hash(key);
+        let shard_id = byte0_to_shard_id(kh[0]);
+        let mut cset = ChangeSet::new();
+        cset.add_op(OP_CREATE, shard_id, &kh, key, value, None);
+        cset.sort();
+        let task = SimpleTask::new(vec![cset]);
+        let tasks = Arc::new(TasksManager::new(vec![RwLock::new(Some(task))], 0));
+        ads.start_block(height, tasks);
+        let shared = ads.get_shared();
+        shared.add_task(height << IN_BLOCK_IDX_BITS);
+        ads.flush();
+    }
+
+    fn current_roots(ads: &AdsWrap<SimpleTask>) -> [[u8; 32]; SHARD_COUNT] {
+        let metadb = ads.get_metadb();
+        let metadb = metadb.read();
+        let mut roots = [[0u8; 32]; SHARD_COUNT];
+        for (shard_id, root) in roots.iter_mut().enumerate() {
+            *root = metadb.get_root(shard_id as u8);
+        }
+        roots
+    }
+
+    #[test]
+    fn import_accepts_an_untampered_snapshot() {
+        let config = Config::in_memory();
+        let mut ads = AdsWrap::<SimpleTask>::new(&config);
+        write_one_block(&mut ads, 1, b"k", b"v");
+
+        let height = ads.get_metadb().read().get_curr_height();
+        let expected_roots = current_roots(&ads);
+        let chunks = ads.get_shared().export_snapshot(height, ShardRange::all());
+
+        let imported = AdsWrap::<SimpleTask>::import_snapshot(&config, height, expected_roots, chunks)
+            .expect("an untampered snapshot must import cleanly");
+
+        let mut buf = [0u8; 64];
+        let kh = hasher::hash(b"k");
+        let (n, ok) = imported.get_shared().read_entry(-1, &kh, b"k", &mut buf);
+        assert!(ok);
+        assert_eq!(EntryBz { bz: &buf[..n] }.value(), b"v");
+    }
+
+    #[test]
+    fn import_roundtrips_a_shard_with_several_keys() {
+        // Regression test: `export_chunk` used to iterate a `HashMap` with
+        // no defined order, and `import_snapshot` assigned twig leaf
+        // positions by the order entries were replayed in. Since
+        // `TwigTree::root` folds leaves with a non-commutative hash, that
+        // meant re-importing a shard holding more than one merkelized key
+        // could legitimately fail with `RootMismatch` even though nothing
+        // was tampered with. A single key (as in the tests above) never
+        // exercised this, so find several keys that land in the same
+        // shard and write them all before exporting.
+        let config = Config::in_memory();
+        let mut ads = AdsWrap::<SimpleTask>::new(&config);
+
+        let mut height = 1;
+        let mut found = 0;
+        let mut candidate = 0u32;
+        while found < 6 {
+            let key = candidate.to_le_bytes().to_vec();
+            let kh = hasher::hash(&key);
+            if byte0_to_shard_id(kh[0]) == 0 {
+                write_one_block(&mut ads, height, &key, b"v");
+                height += 1;
+                found += 1;
+            }
+            candidate += 1;
+        }
+
+        let curr_height = ads.get_metadb().read().get_curr_height();
+        let expected_roots = current_roots(&ads);
+        let chunks = ads.get_shared().export_snapshot(curr_height, ShardRange::all());
+
+        AdsWrap::<SimpleTask>::import_snapshot(&config, curr_height, expected_roots, chunks)
+            .expect("a shard with several merkelized keys must still import cleanly");
+    }
+
+    #[test]
+    fn import_rejects_a_tampered_chunk() {
+        let config = Config::in_memory();
+        let mut ads = AdsWrap::<SimpleTask>::new(&config);
+        write_one_block(&mut ads, 1, b"k", b"v");
+
+        let height = ads.get_metadb().read().get_curr_height();
+        let expected_roots = current_roots(&ads);
+        let mut chunks: Vec<_> = ads.get_shared().export_snapshot(height, ShardRange::all()).collect();
+        let tampered = chunks
+            .iter_mut()
+            .find_map(|c| c.entries.first_mut())
+            .expect("the written key must appear in some chunk");
+        tampered.value = b"tampered".to_vec();
+
+        let result = AdsWrap::<SimpleTask>::import_snapshot(&config, height, expected_roots, chunks.into_iter());
+        assert!(matches!(result, Err(SnapshotError::RootMismatch { .. })));
+    }
+}
@@ -0,0 +1,88 @@
+//! The original on-disk [`super::KvBackend`]: one append-only file per
+//! shard, plus one small file per metadata key.
+
+use std::collections::HashMap;
+use std::fs::{self, File, OpenOptions};
+use std::io::{Read, Seek, SeekFrom, Write};
+use std::path::PathBuf;
+use std::sync::Mutex;
+
+use super::KvBackend;
+
+pub struct FileBackend {
+    dir: PathBuf,
+    shard_files: Mutex<HashMap<u8, File>>,
+}
+
+impl FileBackend {
+    pub fn open(dir: impl Into<PathBuf>) -> std::io::Result<Self> {
+        let dir = dir.into();
+        fs::create_dir_all(dir.join("meta"))?;
+        Ok(Self {
+            dir,
+            shard_files: Mutex::new(HashMap::new()),
+        })
+    }
+
+    fn meta_path(&self, key: &str) -> PathBuf {
+        self.dir.join("meta").join(key)
+    }
+
+    fn with_shard_file<R>(&self, shard_id: u8, f: impl FnOnce(&mut File) -> std::io::Result<R>) -> std::io::Result<R> {
+        let mut files = self.shard_files.lock().unwrap();
+        let file = match files.entry(shard_id) {
+            std::collections::hash_map::Entry::Occupied(e) => e.into_mut(),
+            std::collections::hash_map::Entry::Vacant(e) => {
+                let path = self.dir.join(format!("entries.{shard_id}"));
+                let file = OpenOptions::new().create(true).read(true).append(true).open(path)?;
+                e.insert(file)
+            }
+        };
+        f(file)
+    }
+}
+
+impl KvBackend for FileBackend {
+    fn append(&self, shard_id: u8, bytes: &[u8]) -> std::io::Result<u64> {
+        self.with_shard_file(shard_id, |file| {
+            let offset = file.seek(SeekFrom::End(0))?;
+            file.write_all(bytes)?;
+            Ok(offset)
+        })
+    }
+
+    fn read_at(&self, shard_id: u8, offset: u64, buf: &mut [u8]) -> std::io::Result<usize> {
+        self.with_shard_file(shard_id, |file| {
+            file.seek(SeekFrom::Start(offset))?;
+            let mut total = 0;
+            loop {
+                match file.read(&mut buf[total..]) {
+                    Ok(0) => break,
+                    Ok(n) => total += n,
+                    Err(e) => return Err(e),
+                }
+            }
+            Ok(total)
+        })
+    }
+
+    fn replace_log(&self, shard_id: u8, bytes: &[u8]) -> std::io::Result<()> {
+        self.with_shard_file(shard_id, |file| {
+            file.set_len(0)?;
+            file.seek(SeekFrom::Start(0))?;
+            file.write_all(bytes)
+        })
+    }
+
+    fn get_meta(&self, key: &str) -> std::io::Result<Option<Vec<u8>>> {
+        match fs::read(self.meta_path(key)) {
+            Ok(bytes) => Ok(Some(bytes)),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(None),
+            Err(e) => Err(e),
+        }
+    }
+
+    fn set_meta(&self, key: &str, value: &[u8]) -> std::io::Result<()> {
+        fs::write(self.meta_path(key), value)
+    }
+}
@@ -0,0 +1,32 @@
+//! Abstraction over where raw bytes physically live, so the Merkle/twig
+//! logic in [`crate::shard`] and [`crate::metadb`] doesn't need to care
+//! whether it's talking to disk or memory.
+
+pub mod file;
+pub mod memory;
+
+pub use file::FileBackend;
+pub use memory::MemBackend;
+
+/// Storage primitive every shard's entry log and the metadb are built on:
+/// an append-only byte log per shard, plus a small flat key/value space for
+/// metadata (current height, per-shard roots).
+pub trait KvBackend: Send + Sync {
+    /// Appends `bytes` to shard `shard_id`'s log, returning the offset it
+    /// was written at.
+    fn append(&self, shard_id: u8, bytes: &[u8]) -> std::io::Result<u64>;
+
+    /// Reads up to `buf.len()` bytes of shard `shard_id`'s log starting at
+    /// `offset`, returning how many bytes were actually available and
+    /// copied.
+    fn read_at(&self, shard_id: u8, offset: u64, buf: &mut [u8]) -> std::io::Result<usize>;
+
+    /// Discards shard `shard_id`'s entire log and replaces it with `bytes`.
+    /// Used by compaction to physically drop pruned versions instead of
+    /// leaving them resident in the log forever; callers are responsible
+    /// for fixing up any offsets they'd recorded into the old log.
+    fn replace_log(&self, shard_id: u8, bytes: &[u8]) -> std::io::Result<()>;
+
+    fn get_meta(&self, key: &str) -> std::io::Result<Option<Vec<u8>>>;
+    fn set_meta(&self, key: &str, value: &[u8]) -> std::io::Result<()>;
+}
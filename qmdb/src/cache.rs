@@ -0,0 +1,332 @@
+//! Bounded in-memory cache of serialized entry bytes. Preloaded at startup
+//! (see [`crate::shard::ShardStore::open`]) and kept warm by reads and
+//! writes after that, with a pluggable [`AccessTracker`] deciding what to
+//! evict once the cache grows past its [`SizeTargets`]. Reads take a
+//! shared lock on the shard, so the cache protects its own state with an
+//! internal mutex rather than requiring `&mut self`.
+
+use std::collections::{HashMap, HashSet};
+use std::time::Instant;
+
+use parking_lot::Mutex;
+
+/// Soft/hard ceilings on what an [`EntryCache`] is allowed to hold. `None`
+/// means "no limit on this dimension". Hitting a hard limit triggers
+/// eviction back down to the soft limit (or to zero slack under the hard
+/// limit if no soft limit is set), so a cache doesn't thrash evicting one
+/// entry per insert right at the boundary.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct SizeTargets {
+    pub soft_bytes: Option<usize>,
+    pub hard_bytes: Option<usize>,
+    pub soft_entries: Option<usize>,
+    pub hard_entries: Option<usize>,
+}
+
+impl SizeTargets {
+    pub fn bytes(soft: usize, hard: usize) -> Self {
+        Self {
+            soft_bytes: Some(soft),
+            hard_bytes: Some(hard),
+            ..Default::default()
+        }
+    }
+}
+
+/// Decides which cached entry to evict first when the cache is over its
+/// size targets.
+pub trait AccessTracker: Send {
+    fn on_access(&mut self, key_hash: [u8; 32]);
+    fn on_insert(&mut self, key_hash: [u8; 32]);
+    fn on_remove(&mut self, key_hash: [u8; 32]);
+    /// The least valuable cached key to evict next, excluding anything in
+    /// `protected`. `None` if every cached key is protected.
+    fn evict_candidate(&self, protected: &HashSet<[u8; 32]>) -> Option<[u8; 32]>;
+}
+
+/// Evicts the least-recently-accessed entry.
+#[derive(Default)]
+pub struct LruTracker {
+    last_access: HashMap<[u8; 32], Instant>,
+}
+
+impl AccessTracker for LruTracker {
+    fn on_access(&mut self, key_hash: [u8; 32]) {
+        self.last_access.insert(key_hash, Instant::now());
+    }
+
+    fn on_insert(&mut self, key_hash: [u8; 32]) {
+        self.on_access(key_hash);
+    }
+
+    fn on_remove(&mut self, key_hash: [u8; 32]) {
+        self.last_access.remove(&key_hash);
+    }
+
+    fn evict_candidate(&self, protected: &HashSet<[u8; 32]>) -> Option<[u8; 32]> {
+        self.last_access
+            .iter()
+            .filter(|(k, _)| !protected.contains(*k))
+            .min_by_key(|(_, t)| **t)
+            .map(|(k, _)| *k)
+    }
+}
+
+/// Evicts the least-frequently-accessed entry.
+#[derive(Default)]
+pub struct FrequencyTracker {
+    access_count: HashMap<[u8; 32], u64>,
+}
+
+impl AccessTracker for FrequencyTracker {
+    fn on_access(&mut self, key_hash: [u8; 32]) {
+        *self.access_count.entry(key_hash).or_insert(0) += 1;
+    }
+
+    fn on_insert(&mut self, key_hash: [u8; 32]) {
+        self.access_count.entry(key_hash).or_insert(0);
+    }
+
+    fn on_remove(&mut self, key_hash: [u8; 32]) {
+        self.access_count.remove(&key_hash);
+    }
+
+    fn evict_candidate(&self, protected: &HashSet<[u8; 32]>) -> Option<[u8; 32]> {
+        self.access_count
+            .iter()
+            .filter(|(k, _)| !protected.contains(*k))
+            .min_by_key(|(_, count)| **count)
+            .map(|(k, _)| *k)
+    }
+}
+
+/// Hit/miss/eviction counters for a cache, so operators can judge whether
+/// its size targets are actually sized for the working set.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct CacheStats {
+    pub hits: u64,
+    pub misses: u64,
+    pub evicted_bytes: u64,
+}
+
+struct Inner {
+    entries: HashMap<[u8; 32], Vec<u8>>,
+    used_bytes: usize,
+    tracker: Box<dyn AccessTracker>,
+    /// Keys written by the in-flight block; protected from eviction until
+    /// the block finishes flushing, so a long block never evicts data it
+    /// just wrote before it's durable elsewhere.
+    dirty: HashSet<[u8; 32]>,
+    stats: CacheStats,
+}
+
+pub struct EntryCache {
+    targets: SizeTargets,
+    inner: Mutex<Inner>,
+}
+
+impl EntryCache {
+    pub fn new(targets: SizeTargets, tracker: Box<dyn AccessTracker>) -> Self {
+        Self {
+            targets,
+            inner: Mutex::new(Inner {
+                entries: HashMap::new(),
+                used_bytes: 0,
+                tracker,
+                dirty: HashSet::new(),
+                stats: CacheStats::default(),
+            }),
+        }
+    }
+
+    /// Convenience constructor for a byte-bounded LRU cache.
+    pub fn with_budget(budget_bytes: usize) -> Self {
+        Self::new(
+            SizeTargets::bytes(budget_bytes, budget_bytes),
+            Box::new(LruTracker::default()),
+        )
+    }
+
+    fn over_hard_limit(&self, inner: &Inner) -> bool {
+        self.targets.hard_bytes.is_some_and(|max| inner.used_bytes > max)
+            || self.targets.hard_entries.is_some_and(|max| inner.entries.len() > max)
+    }
+
+    fn under_soft_limit(&self, inner: &Inner) -> bool {
+        let bytes_ok = self.targets.soft_bytes.is_none_or(|max| inner.used_bytes <= max);
+        let entries_ok = self.targets.soft_entries.is_none_or(|max| inner.entries.len() <= max);
+        bytes_ok && entries_ok
+    }
+
+    /// Evicts entries, skipping anything currently dirty, until back
+    /// under the soft limit and no longer over the hard limit (or out of
+    /// evictable entries). Only runs at
+    /// all once the hard limit has actually been hit; once it has, it
+    /// evicts all the way down to the soft limit rather than stopping the
+    /// moment the hard limit is no longer exceeded, since dropping it
+    /// partway would otherwise leave the cache parked between soft and
+    /// hard and trigger another eviction pass on the very next insert.
+    fn evict_to_soft_limit(&self, inner: &mut Inner) {
+        if !self.over_hard_limit(inner) {
+            return;
+        }
+        while !self.under_soft_limit(inner) || self.over_hard_limit(inner) {
+            let Some(victim) = inner.tracker.evict_candidate(&inner.dirty) else {
+                break;
+            };
+            if let Some(bytes) = inner.entries.remove(&victim) {
+                inner.used_bytes -= bytes.len();
+                inner.stats.evicted_bytes += bytes.len() as u64;
+                inner.tracker.on_remove(victim);
+            }
+        }
+    }
+
+    /// Caches `bytes` for `key_hash`, evicting other entries if that pushes
+    /// the cache over its hard limit. Returns whether it ended up cached
+    /// (it may not, if every other entry is dirty and protected).
+    pub fn try_insert(&self, key_hash: [u8; 32], bytes: &[u8]) -> bool {
+        let mut inner = self.inner.lock();
+        if let Some(old) = inner.entries.remove(&key_hash) {
+            inner.used_bytes -= old.len();
+        }
+        inner.entries.insert(key_hash, bytes.to_vec());
+        inner.used_bytes += bytes.len();
+        inner.tracker.on_insert(key_hash);
+
+        self.evict_to_soft_limit(&mut inner);
+        inner.entries.contains_key(&key_hash)
+    }
+
+    /// Replaces the cached bytes for `key_hash` after a fresh write and
+    /// marks it dirty so it can't be evicted until [`Self::clear_dirty`]
+    /// runs at the end of the block.
+    pub fn update(&self, key_hash: [u8; 32], bytes: &[u8]) {
+        self.inner.lock().dirty.insert(key_hash);
+        self.try_insert(key_hash, bytes);
+    }
+
+    /// Releases the eviction-protection held by everything written during
+    /// the block that just finished flushing.
+    pub fn clear_dirty(&self) {
+        self.inner.lock().dirty.clear();
+    }
+
+    pub fn get(&self, key_hash: &[u8; 32]) -> Option<Vec<u8>> {
+        let mut inner = self.inner.lock();
+        if inner.entries.contains_key(key_hash) {
+            inner.tracker.on_access(*key_hash);
+            inner.stats.hits += 1;
+            inner.entries.get(key_hash).cloned()
+        } else {
+            inner.stats.misses += 1;
+            None
+        }
+    }
+
+    pub fn resident_bytes(&self) -> usize {
+        self.inner.lock().used_bytes
+    }
+
+    pub fn entry_count(&self) -> usize {
+        self.inner.lock().entries.len()
+    }
+
+    pub fn stats(&self) -> CacheStats {
+        self.inner.lock().stats
+    }
+}
+
+/// Startup preload metrics for one shard, so operators can size the
+/// cache's [`SizeTargets`] against the working set it actually holds.
+#[derive(Clone, Copy, Debug)]
+pub struct CacheLoadReport {
+    pub shard_id: u8,
+    pub entries_scanned: usize,
+    pub resident_bytes: usize,
+    pub load_time: std::time::Duration,
+}
+
+/// Per-shard [`CacheLoadReport`]s summed across every shard, so operators
+/// can size the cache without having to aggregate the per-shard reports
+/// themselves.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct CacheLoadSummary {
+    pub total_resident_bytes: usize,
+    pub total_load_time: std::time::Duration,
+}
+
+impl CacheLoadSummary {
+    pub fn add(&mut self, report: &CacheLoadReport) {
+        self.total_resident_bytes += report.resident_bytes;
+        self.total_load_time += report.load_time;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn cache_load_summary_sums_reports_across_shards() {
+        let mut summary = CacheLoadSummary::default();
+        summary.add(&CacheLoadReport {
+            shard_id: 0,
+            entries_scanned: 3,
+            resident_bytes: 100,
+            load_time: std::time::Duration::from_millis(5),
+        });
+        summary.add(&CacheLoadReport {
+            shard_id: 1,
+            entries_scanned: 2,
+            resident_bytes: 50,
+            load_time: std::time::Duration::from_millis(7),
+        });
+
+        assert_eq!(summary.total_resident_bytes, 150);
+        assert_eq!(summary.total_load_time, std::time::Duration::from_millis(12));
+    }
+
+    #[test]
+    fn evicts_down_to_soft_limit_once_over_hard_limit() {
+        let cache = EntryCache::new(
+            SizeTargets {
+                soft_bytes: Some(2),
+                hard_bytes: Some(3),
+                ..Default::default()
+            },
+            Box::new(LruTracker::default()),
+        );
+        cache.try_insert([1u8; 32], &[0u8; 1]);
+        cache.try_insert([2u8; 32], &[0u8; 1]);
+        cache.try_insert([3u8; 32], &[0u8; 1]);
+        cache.try_insert([4u8; 32], &[0u8; 1]);
+
+        assert!(cache.resident_bytes() <= 2);
+        assert!(cache.entry_count() <= 2);
+        assert!(cache.stats().evicted_bytes > 0);
+    }
+
+    #[test]
+    fn dirty_entries_are_protected_from_eviction() {
+        let cache = EntryCache::new(
+            SizeTargets {
+                soft_bytes: Some(1),
+                hard_bytes: Some(1),
+                ..Default::default()
+            },
+            Box::new(LruTracker::default()),
+        );
+        cache.update([1u8; 32], &[0u8; 1]);
+        cache.try_insert([2u8; 32], &[0u8; 1]);
+
+        // Over budget, but the dirty entry survives eviction.
+        assert!(cache.get(&[1u8; 32]).is_some());
+
+        // Once it's no longer dirty, a fresh insert can evict it.
+        cache.clear_dirty();
+        cache.try_insert([3u8; 32], &[0u8; 1]);
+        assert!(cache.get(&[1u8; 32]).is_none());
+        assert!(cache.get(&[3u8; 32]).is_some());
+    }
+}
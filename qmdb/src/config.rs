@@ -0,0 +1,127 @@
+//! Tunables that control how an [`crate::AdsWrap`] lays out and retains
+//! data on disk.
+
+use std::path::PathBuf;
+use std::sync::Arc;
+
+use crate::cache::SizeTargets;
+use crate::def::{DEFAULT_SHARD_MEMORY_BUDGET_BYTES, DEFAULT_VER_WINDOW};
+
+/// Which [`crate::cache::AccessTracker`] a shard's read cache uses to pick
+/// eviction victims.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Default)]
+pub enum TrackerKind {
+    /// Evict the least-recently-accessed entry. Good default for working
+    /// sets with temporal locality.
+    #[default]
+    Lru,
+    /// Evict the least-frequently-accessed entry. Better when a small set
+    /// of keys is read far more often than the rest, regardless of when.
+    Frequency,
+}
+
+/// A key predicate deciding whether a key's entries get folded into the
+/// Twig Merkle Tree. See [`Config::is_merkelized`].
+pub type MerkelizeFilter = Arc<dyn Fn(&[u8]) -> bool + Send + Sync>;
+
+/// Selects which [`crate::backend::KvBackend`] an [`crate::AdsWrap`] stores
+/// its shards and metadb on.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Default)]
+pub enum Backend {
+    /// One append-only file per shard under `dir`, as before. The durable,
+    /// default choice.
+    #[default]
+    File,
+    /// Everything held in process memory; nothing under `dir` is touched.
+    /// Useful for unit tests and ephemeral instances.
+    Memory,
+}
+
+#[derive(Clone)]
+pub struct Config {
+    /// Root directory holding the entryfiles and metadb. Unused when
+    /// `backend` is [`Backend::Memory`].
+    pub dir: PathBuf,
+
+    /// Which [`KvBackend`](crate::backend::KvBackend) implementation to
+    /// store shards and metadb on.
+    pub backend: Backend,
+
+    /// Number of recent block heights for which old entry versions are
+    /// kept around. A point-in-time read at height `H` only succeeds if
+    /// `H >= curr_height - ver_window`; older versions are discarded by
+    /// the prune step that runs as part of [`crate::AdsWrap::flush`].
+    pub ver_window: u64,
+
+    /// Decides whether a key's writes are hashed into the twig tree.
+    /// Keys this returns `false` for are still durably stored and
+    /// readable via `read_entry`, but are never folded into a root, so
+    /// they're not provable and carry no per-block hashing cost. Defaults
+    /// to merkelizing everything.
+    pub is_merkelized: MerkelizeFilter,
+
+    /// When set, `AdsWrap::new` preloads each shard's active entries into
+    /// an in-memory cache so `read_entry` can serve hot state without
+    /// touching the backend, falling back to it on a cache miss.
+    pub load_in_memory: bool,
+
+    /// Per-shard soft/hard limits on how big the read cache is allowed to
+    /// grow. Exceeding the hard limit triggers eviction back down to the
+    /// soft limit; the preload step also streams entries in so loading
+    /// never blows past these limits either.
+    pub cache_size_targets: SizeTargets,
+
+    /// Which eviction policy the per-shard read cache uses once it's
+    /// over its `cache_size_targets`.
+    pub cache_tracker: TrackerKind,
+}
+
+impl Config {
+    pub fn from_dir(dir: impl Into<PathBuf>) -> Self {
+        Self {
+            dir: dir.into(),
+            backend: Backend::default(),
+            ver_window: DEFAULT_VER_WINDOW,
+            is_merkelized: Arc::new(|_key| true),
+            load_in_memory: false,
+            cache_size_targets: SizeTargets::bytes(
+                DEFAULT_SHARD_MEMORY_BUDGET_BYTES,
+                DEFAULT_SHARD_MEMORY_BUDGET_BYTES,
+            ),
+            cache_tracker: TrackerKind::default(),
+        }
+    }
+
+    /// Builds a config for a purely in-memory, ephemeral instance.
+    pub fn in_memory() -> Self {
+        Self {
+            backend: Backend::Memory,
+            ..Self::from_dir(PathBuf::new())
+        }
+    }
+
+    pub fn with_ver_window(mut self, ver_window: u64) -> Self {
+        self.ver_window = ver_window;
+        self
+    }
+
+    /// Excludes keys for which `filter` returns `false` from merkelization.
+    pub fn with_merkelize_filter(mut self, filter: MerkelizeFilter) -> Self {
+        self.is_merkelized = filter;
+        self
+    }
+
+    /// Enables the startup preload cache with the given per-shard size
+    /// targets.
+    pub fn with_in_memory_cache(mut self, targets: SizeTargets) -> Self {
+        self.load_in_memory = true;
+        self.cache_size_targets = targets;
+        self
+    }
+
+    /// Chooses the eviction policy for the per-shard read cache.
+    pub fn with_cache_tracker(mut self, tracker: TrackerKind) -> Self {
+        self.cache_tracker = tracker;
+        self
+    }
+}
@@ -0,0 +1,29 @@
+//! Constants shared across the storage engine.
+
+/// Default size, in bytes, of the scratch buffer callers allocate before
+/// they know an entry's exact serialized length.
+pub const DEFAULT_ENTRY_SIZE: usize = 256;
+
+/// Number of low bits of a task id reserved for the in-block task index;
+/// the remaining high bits encode the block height.
+pub const IN_BLOCK_IDX_BITS: i64 = 24;
+
+/// Number of first-level shards the key space is partitioned into.
+pub const SHARD_COUNT: usize = 16;
+
+/// Change-set operation kinds.
+pub const OP_CREATE: u8 = 0;
+pub const OP_WRITE: u8 = 1;
+pub const OP_DELETE: u8 = 2;
+
+/// Sentinel height meaning "the latest committed height", used throughout
+/// the read path so callers don't need to know the current height.
+pub const LATEST_HEIGHT: i64 = -1;
+
+/// Default number of recent block heights for which old entry versions are
+/// retained before being pruned. See [`crate::config::Config::ver_window`].
+pub const DEFAULT_VER_WINDOW: u64 = 100_000;
+
+/// Default per-shard budget for the startup preload cache.
+/// See [`crate::config::Config::cache_size_targets`].
+pub const DEFAULT_SHARD_MEMORY_BUDGET_BYTES: usize = 64 * 1024 * 1024;
@@ -0,0 +1,127 @@
+//! Append-only log of entries, plus the zero-copy view used to read them
+//! back out of a caller-supplied buffer. The physical bytes live wherever
+//! the shard's [`crate::backend::KvBackend`] puts them.
+
+use std::sync::Arc;
+
+use crate::backend::KvBackend;
+
+/// An entry as it exists before being serialized into an [`EntryFile`].
+#[derive(Clone, Debug)]
+pub struct Entry {
+    pub key: Vec<u8>,
+    pub value: Vec<u8>,
+    /// Block height at which this version of the entry was committed.
+    pub height: u64,
+    /// Whether this version was folded into the shard's twig tree. Persisted
+    /// alongside the entry (rather than re-derived) so a restart-time
+    /// rebuild of the tree (see [`crate::shard::ShardStore::open`]) folds in
+    /// exactly the same versions the live tree did.
+    pub merkelize: bool,
+}
+
+impl Entry {
+    /// Serialized layout: `[key_len: u32][key][value_len: u32][value][height: u64][merkelize: u8]`.
+    pub fn serialize(&self) -> Vec<u8> {
+        let mut out = Vec::with_capacity(8 + self.key.len() + self.value.len() + 9);
+        out.extend_from_slice(&(self.key.len() as u32).to_le_bytes());
+        out.extend_from_slice(&self.key);
+        out.extend_from_slice(&(self.value.len() as u32).to_le_bytes());
+        out.extend_from_slice(&self.value);
+        out.extend_from_slice(&self.height.to_le_bytes());
+        out.push(self.merkelize as u8);
+        out
+    }
+}
+
+/// Borrowed, zero-copy view over a serialized [`Entry`]'s bytes, as read
+/// into a caller-supplied buffer.
+pub struct EntryBz<'a> {
+    pub bz: &'a [u8],
+}
+
+impl<'a> EntryBz<'a> {
+    pub fn key_len(&self) -> usize {
+        u32::from_le_bytes(self.bz[0..4].try_into().unwrap()) as usize
+    }
+
+    pub fn key(&self) -> &[u8] {
+        &self.bz[4..4 + self.key_len()]
+    }
+
+    fn value_len_offset(&self) -> usize {
+        4 + self.key_len()
+    }
+
+    pub fn value_len(&self) -> usize {
+        let off = self.value_len_offset();
+        u32::from_le_bytes(self.bz[off..off + 4].try_into().unwrap()) as usize
+    }
+
+    pub fn value(&self) -> &[u8] {
+        let off = self.value_len_offset() + 4;
+        &self.bz[off..off + self.value_len()]
+    }
+
+    pub fn height(&self) -> u64 {
+        let off = self.value_len_offset() + 4 + self.value_len();
+        u64::from_le_bytes(self.bz[off..off + 8].try_into().unwrap())
+    }
+
+    fn merkelize_offset(&self) -> usize {
+        self.value_len_offset() + 4 + self.value_len() + 8
+    }
+
+    /// Whether this version was folded into the twig tree when it was
+    /// written. See [`Entry::merkelize`].
+    pub fn merkelize(&self) -> bool {
+        self.bz[self.merkelize_offset()] != 0
+    }
+
+    /// Total length in bytes of the serialized entry this view was read
+    /// from, i.e. how much of `bz` is actually occupied.
+    pub fn len(&self) -> usize {
+        self.merkelize_offset() + 1
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+}
+
+/// Append-only log backing one shard. Entries are never overwritten in
+/// place; superseded versions are left in the log until pruned. The
+/// physical storage is delegated to a [`KvBackend`] so the same logic
+/// works whether that's a file or an in-memory buffer.
+pub struct EntryFile {
+    backend: Arc<dyn KvBackend>,
+    shard_id: u8,
+}
+
+impl EntryFile {
+    pub fn new(backend: Arc<dyn KvBackend>, shard_id: u8) -> Self {
+        Self { backend, shard_id }
+    }
+
+    /// Appends `entry` to the log and returns the offset it was written
+    /// at, which callers index by for later reads.
+    pub fn append(&self, entry: &Entry) -> std::io::Result<u64> {
+        self.backend.append(self.shard_id, &entry.serialize())
+    }
+
+    /// Reads the entry at `offset` into `buf`, returning how many bytes
+    /// were used. `buf` must be large enough to hold the whole entry;
+    /// callers size it from [`crate::def::DEFAULT_ENTRY_SIZE`].
+    pub fn read_at(&self, offset: u64, buf: &mut [u8]) -> std::io::Result<usize> {
+        let available = self.backend.read_at(self.shard_id, offset, buf)?;
+        let head = EntryBz { bz: &buf[..available] };
+        Ok(head.len())
+    }
+
+    /// Discards the log's entire contents and replaces them with `bytes`.
+    /// Used by compaction; callers must fix up any offsets they'd recorded
+    /// into the old log, since every entry's position can change.
+    pub fn replace_log(&self, bytes: &[u8]) -> std::io::Result<()> {
+        self.backend.replace_log(self.shard_id, bytes)
+    }
+}
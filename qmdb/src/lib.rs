@@ -0,0 +1,19 @@
+//! QMDB: an authenticated key-value store built around a Twig Merkle Tree,
+//! organized as a pipeline of per-block tasks applied to sharded,
+//! append-only storage.
+
+mod ads;
+pub mod backend;
+pub mod cache;
+pub mod config;
+pub mod def;
+pub mod entryfile;
+pub mod merkle;
+pub mod metadb;
+mod shard;
+pub mod snapshot;
+pub mod tasks;
+pub mod test_helper;
+pub mod utils;
+
+pub use ads::{AdsCore, AdsWrap, SharedAds, ADS};
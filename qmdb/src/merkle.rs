@@ -0,0 +1,66 @@
+//! A simplified Twig Merkle Tree: the authenticated structure whose root
+//! commits to every merkelized entry currently live in a shard.
+//!
+//! Real twig trees batch leaves into fixed-size "twigs" to make pruning and
+//! partial updates cheap; this module keeps the leaf set as a flat,
+//! position-indexed vector and recomputes the root by folding leaves
+//! pairwise. That's the detail later work (caching, snapshotting) builds
+//! on top of without needing to change.
+
+use crate::utils::hasher::hash2;
+
+/// One leaf slot in the tree. `None` marks a slot whose entry was excluded
+/// from merkelization or has been deleted.
+#[derive(Clone, Copy, Debug)]
+pub struct Leaf(pub Option<[u8; 32]>);
+
+#[derive(Default)]
+pub struct TwigTree {
+    leaves: Vec<Leaf>,
+}
+
+impl TwigTree {
+    pub fn new() -> Self {
+        Self { leaves: Vec::new() }
+    }
+
+    /// Sets the leaf at `pos`, growing the tree if necessary.
+    pub fn set_leaf(&mut self, pos: usize, hash: Option<[u8; 32]>) {
+        if pos >= self.leaves.len() {
+            self.leaves.resize(pos + 1, Leaf(None));
+        }
+        self.leaves[pos] = Leaf(hash);
+    }
+
+    pub fn push_leaf(&mut self, hash: Option<[u8; 32]>) -> usize {
+        let pos = self.leaves.len();
+        self.leaves.push(Leaf(hash));
+        pos
+    }
+
+    /// Folds all present leaves into a single root hash. Absent leaves
+    /// (deleted or non-merkelized entries) are treated as the zero digest.
+    pub fn root(&self) -> [u8; 32] {
+        if self.leaves.is_empty() {
+            return [0u8; 32];
+        }
+        let mut level: Vec<[u8; 32]> = self
+            .leaves
+            .iter()
+            .map(|l| l.0.unwrap_or([0u8; 32]))
+            .collect();
+        while level.len() > 1 {
+            let mut next = Vec::with_capacity(level.len().div_ceil(2));
+            for pair in level.chunks(2) {
+                let h = if pair.len() == 2 {
+                    hash2(&pair[0], &pair[1])
+                } else {
+                    pair[0]
+                };
+                next.push(h);
+            }
+            level = next;
+        }
+        level[0]
+    }
+}
@@ -0,0 +1,78 @@
+//! Small durable store for block-level metadata: current height and each
+//! shard's Merkle root. Backed by the same [`crate::backend::KvBackend`]
+//! the shards use, so choosing an in-memory backend makes the whole store
+//! ephemeral. Caller-supplied extra data per block is also kept here, but
+//! only in memory: it is not written to the backend and is not restored
+//! by [`MetaDb::open`].
+
+use std::collections::HashMap;
+use std::sync::Arc;
+
+use crate::backend::KvBackend;
+use crate::def::SHARD_COUNT;
+
+pub struct MetaDb {
+    backend: Arc<dyn KvBackend>,
+    curr_height: i64,
+    roots: [[u8; 32]; SHARD_COUNT],
+    extra_data: HashMap<i64, String>,
+}
+
+const HEIGHT_KEY: &str = "curr_height";
+
+fn root_key(shard_id: u8) -> String {
+    format!("root.{shard_id}")
+}
+
+impl MetaDb {
+    /// Opens the metadb, restoring `curr_height` and the per-shard roots
+    /// from whatever the backend already has persisted.
+    pub fn open(backend: Arc<dyn KvBackend>) -> Self {
+        let curr_height = backend
+            .get_meta(HEIGHT_KEY)
+            .ok()
+            .flatten()
+            .map(|bz| i64::from_le_bytes(bz.try_into().unwrap()))
+            .unwrap_or(0);
+
+        let mut roots = [[0u8; 32]; SHARD_COUNT];
+        for (shard_id, root) in roots.iter_mut().enumerate() {
+            if let Some(bz) = backend.get_meta(&root_key(shard_id as u8)).ok().flatten() {
+                root.copy_from_slice(&bz);
+            }
+        }
+
+        Self {
+            backend,
+            curr_height,
+            roots,
+            extra_data: HashMap::new(),
+        }
+    }
+
+    pub fn get_curr_height(&self) -> i64 {
+        self.curr_height
+    }
+
+    pub fn set_curr_height(&mut self, height: i64) {
+        self.curr_height = height;
+        let _ = self.backend.set_meta(HEIGHT_KEY, &height.to_le_bytes());
+    }
+
+    pub fn get_root(&self, shard_id: u8) -> [u8; 32] {
+        self.roots[shard_id as usize]
+    }
+
+    pub fn set_root(&mut self, shard_id: u8, root: [u8; 32]) {
+        self.roots[shard_id as usize] = root;
+        let _ = self.backend.set_meta(&root_key(shard_id), &root);
+    }
+
+    pub fn insert_extra_data(&mut self, height: i64, data: String) {
+        self.extra_data.insert(height, data);
+    }
+
+    pub fn get_extra_data(&self, height: i64) -> Option<&String> {
+        self.extra_data.get(&height)
+    }
+}
@@ -0,0 +1,125 @@
+//! Streamable export/import of committed state, so a fresh node can catch
+//! up from another node's data instead of replaying every block.
+//!
+//! A snapshot is split into per-shard chunks: each one is a contiguous
+//! key-hash range (a shard, since that's already how the key space is
+//! partitioned) together with the root it must hash up to. Chunks can be
+//! fetched and verified independently and in parallel, and a partially
+//! transferred snapshot can resume by re-exporting just the missing
+//! shards via `range`.
+
+use std::sync::Arc;
+
+use parking_lot::RwLock;
+
+use crate::def::SHARD_COUNT;
+use crate::shard::ShardStore;
+
+/// An inclusive range of shard ids to export, so a transfer can be split
+/// across peers or resumed by asking for only the shards still missing.
+#[derive(Clone, Copy, Debug)]
+pub struct ShardRange {
+    pub start_shard: u8,
+    pub end_shard: u8,
+}
+
+impl ShardRange {
+    pub fn all() -> Self {
+        Self {
+            start_shard: 0,
+            end_shard: (SHARD_COUNT - 1) as u8,
+        }
+    }
+}
+
+/// One key's value as carried in a snapshot chunk.
+#[derive(Clone, Debug)]
+pub struct SnapshotEntry {
+    pub key: Vec<u8>,
+    pub value: Vec<u8>,
+    pub height: u64,
+    /// Whether the exporting shard folded this entry into its twig tree.
+    /// An importer must honor this rather than assume every entry was
+    /// merkelized, or it will fold in keys (deleted or filtered out via
+    /// `Config::is_merkelized`) that never contributed to `root` and the
+    /// recomputed root won't match.
+    pub merkelize: bool,
+    /// This entry's stable twig leaf index in the exporting shard.
+    /// `TwigTree::root` folds leaves pairwise with a non-commutative hash,
+    /// so an importer must place each entry back at this exact position
+    /// rather than assigning positions by the order entries happen to be
+    /// iterated in, or the recomputed root won't match even though every
+    /// entry's contents are intact.
+    pub leaf_pos: usize,
+}
+
+/// A contiguous slice of a snapshot: every live entry in one shard, plus
+/// the root those entries must fold up to. Verifiable on its own given the
+/// root the importer expects for `shard_id` — no sibling hashes from other
+/// shards are needed, since each shard already has its own Merkle root.
+#[derive(Clone, Debug)]
+pub struct Chunk {
+    pub shard_id: u8,
+    pub entries: Vec<SnapshotEntry>,
+    pub root: [u8; 32],
+}
+
+/// Lazily produces one [`Chunk`] per shard in `range`, reading from the
+/// shard stores as each chunk is pulled rather than materializing the
+/// whole snapshot up front.
+pub struct ChunkStream {
+    shards: Arc<Vec<RwLock<ShardStore>>>,
+    next_shard: u8,
+    end_shard: u8,
+}
+
+impl ChunkStream {
+    pub(crate) fn new(shards: Arc<Vec<RwLock<ShardStore>>>, range: ShardRange) -> Self {
+        Self {
+            shards,
+            next_shard: range.start_shard,
+            end_shard: range.end_shard,
+        }
+    }
+}
+
+impl Iterator for ChunkStream {
+    type Item = Chunk;
+
+    fn next(&mut self) -> Option<Chunk> {
+        if self.next_shard > self.end_shard {
+            return None;
+        }
+        let shard_id = self.next_shard;
+        self.next_shard += 1;
+        Some(self.shards[shard_id as usize].read().export_chunk(shard_id))
+    }
+}
+
+/// Errors the importer can hit while replaying a chunk stream.
+#[derive(Debug)]
+pub enum SnapshotError {
+    Io(std::io::Error),
+    /// A chunk's entries didn't hash up to the root the caller expected
+    /// for that shard; the chunk (or the expected root) is untrustworthy.
+    RootMismatch { shard_id: u8 },
+}
+
+impl From<std::io::Error> for SnapshotError {
+    fn from(e: std::io::Error) -> Self {
+        SnapshotError::Io(e)
+    }
+}
+
+impl std::fmt::Display for SnapshotError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            SnapshotError::Io(e) => write!(f, "snapshot io error: {e}"),
+            SnapshotError::RootMismatch { shard_id } => {
+                write!(f, "snapshot chunk for shard {shard_id} didn't match the expected root")
+            }
+        }
+    }
+}
+
+impl std::error::Error for SnapshotError {}
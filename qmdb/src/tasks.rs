@@ -0,0 +1,33 @@
+//! The block-commit pipeline's unit of work.
+
+use parking_lot::RwLock;
+
+use crate::utils::changeset::ChangeSet;
+
+/// A task is an ordered list of change-sets to apply for one logical
+/// operation within a block (e.g. one transaction).
+pub trait Task: Send + Sync + 'static {
+    fn change_sets(&self) -> &[ChangeSet];
+}
+
+/// Holds every task scheduled for a block. Tasks are wrapped in
+/// `RwLock<Option<T>>` so the pipeline can take ownership of each one as
+/// it's pumped through [`crate::ADS::add_task`] while leaving the slot
+/// itself addressable by index.
+pub struct TasksManager<T: Task> {
+    pub task_list: Vec<RwLock<Option<T>>>,
+    pub last_task_id: i64,
+}
+
+impl<T: Task> TasksManager<T> {
+    pub fn new(task_list: Vec<RwLock<Option<T>>>, last_task_id: i64) -> Self {
+        Self {
+            task_list,
+            last_task_id,
+        }
+    }
+
+    pub fn task_count(&self) -> i64 {
+        self.task_list.len() as i64
+    }
+}
@@ -0,0 +1,22 @@
+//! Helpers for examples and tests that don't need a custom [`Task`].
+
+use crate::tasks::Task;
+use crate::utils::changeset::ChangeSet;
+
+/// A [`Task`] that's just the change-sets it applies, with no extra
+/// bookkeeping.
+pub struct SimpleTask {
+    cset_list: Vec<ChangeSet>,
+}
+
+impl SimpleTask {
+    pub fn new(cset_list: Vec<ChangeSet>) -> Self {
+        Self { cset_list }
+    }
+}
+
+impl Task for SimpleTask {
+    fn change_sets(&self) -> &[ChangeSet] {
+        &self.cset_list
+    }
+}
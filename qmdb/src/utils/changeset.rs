@@ -0,0 +1,65 @@
+//! Ordered list of key/value operations produced by one task.
+
+/// A single Create/Write/Delete operation destined for a shard's entry
+/// store. `old_value` is carried along for Write/Delete; the commit path
+/// does not check it against the value currently stored.
+#[derive(Clone, Debug)]
+pub struct Operation {
+    pub op_type: u8,
+    pub shard_id: u8,
+    pub key_hash: [u8; 32],
+    pub key: Vec<u8>,
+    pub value: Vec<u8>,
+    pub old_value: Option<Vec<u8>>,
+}
+
+/// An ordered, mutable batch of operations belonging to one task. Operations
+/// must be [`ChangeSet::sort`]ed by key-hash before being handed to the
+/// commit pipeline so shards can apply them in a deterministic order.
+#[derive(Clone, Debug, Default)]
+pub struct ChangeSet {
+    ops: Vec<Operation>,
+}
+
+impl ChangeSet {
+    pub fn new() -> Self {
+        Self { ops: Vec::new() }
+    }
+
+    /// Appends an operation to this change-set. `key_hash` must be exactly
+    /// 32 bytes, as produced by [`crate::utils::hasher::hash`].
+    pub fn add_op(
+        &mut self,
+        op_type: u8,
+        shard_id: u8,
+        key_hash: &[u8],
+        key: &[u8],
+        value: &[u8],
+        old_value: Option<&[u8]>,
+    ) {
+        let mut kh = [0u8; 32];
+        kh.copy_from_slice(&key_hash[..32]);
+        self.ops.push(Operation {
+            op_type,
+            shard_id,
+            key_hash: kh,
+            key: key.to_vec(),
+            value: value.to_vec(),
+            old_value: old_value.map(|v| v.to_vec()),
+        });
+    }
+
+    /// Sorts operations by key-hash, which is the order the commit pipeline
+    /// expects so entries land in the twig tree deterministically.
+    pub fn sort(&mut self) {
+        self.ops.sort_by_key(|op| op.key_hash);
+    }
+
+    pub fn ops(&self) -> &[Operation] {
+        &self.ops
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.ops.is_empty()
+    }
+}
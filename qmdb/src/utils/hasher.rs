@@ -0,0 +1,16 @@
+//! Keyed hashing used to derive key-hashes and entry digests.
+
+/// Hashes `data` down to the 32-byte digest used as a key-hash or Merkle
+/// leaf value throughout the store.
+pub fn hash(data: &[u8]) -> [u8; 32] {
+    blake3::hash(data).into()
+}
+
+/// Hashes two 32-byte digests together, used when folding twig leaves into
+/// a Merkle root.
+pub fn hash2(left: &[u8; 32], right: &[u8; 32]) -> [u8; 32] {
+    let mut buf = [0u8; 64];
+    buf[..32].copy_from_slice(left);
+    buf[32..].copy_from_slice(right);
+    hash(&buf)
+}
@@ -0,0 +1,10 @@
+pub mod changeset;
+pub mod hasher;
+
+use crate::def::SHARD_COUNT;
+
+/// Maps the first byte of a key-hash to a shard id, partitioning the key
+/// space roughly evenly across `SHARD_COUNT` shards.
+pub fn byte0_to_shard_id(byte0: u8) -> u8 {
+    ((byte0 as usize) * SHARD_COUNT / 256) as u8
+}